@@ -1,72 +1,286 @@
 use std::sync::Arc;
+use std::time::Instant;
 
 use axum::{
-    body::Bytes,
+    body::Body,
     extract::{Path, State},
-    http::StatusCode,
+    http::{HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
     routing::{get, post},
     Router,
 };
-use local_lru::LocalCache;
+use metrics_exporter_prometheus::PrometheusHandle;
+use store::cache::{EvictionPolicy, LocalCache};
+use store::checksum::{Checksum, ChecksumAlgorithm};
+use store::chunking::ChunkingConfig;
+use store::redis_store::{CacheValue, RedisStore};
 use store::s3_store::S3Store;
 use store::s3_store::StoreError;
 use tracing::info;
+mod metrics;
 mod store;
 
+const REDIS_TTL_SECONDS: u64 = 120;
+/// Objects larger than this are streamed straight through to the client
+/// instead of being buffered into `LocalCache`/Redis.
+const DEFAULT_MAX_CACHEABLE_BYTES: u64 = 5 * 1024 * 1024;
+/// Hard cap on a single chunked upload buffered into memory before being
+/// split into content-defined chunks. Chunking still needs the whole body in
+/// memory to compute chunk boundaries, so this is a backstop against an
+/// unbounded allocation rather than a true streaming limit.
+const MAX_CHUNKED_UPLOAD_BYTES: usize = 1024 * 1024 * 1024;
+const CHECKSUM_HEADER: &str = "x-content-checksum";
+
 struct Services {
     store: S3Store,
     cache: LocalCache,
+    redis: Option<RedisStore>,
+    max_cacheable_bytes: u64,
+    /// `Some` puts every key in content-defined-chunked mode; see `chunking_config`.
+    chunking: Option<ChunkingConfig>,
+    metrics_handle: PrometheusHandle,
 }
 
 #[tokio::main]
 async fn main() {
     tracing_subscriber::fmt().with_ansi(true).init();
     info!("Starting server");
-    let store = S3Store::new("somebucket", "phi3").await;
-    let cache = LocalCache::new(1000, 120);
-    let services = Arc::new(Services { store, cache });
+    let metrics_handle = metrics::install();
+    let store = S3Store::new("somebucket", "phi3", checksum_algorithm()).await;
+    let cache = LocalCache::new(1000, 120, eviction_policy());
+    let redis = init_redis().await;
+    let max_cacheable_bytes = max_cacheable_bytes();
+    let chunking = chunking_config();
+    let services = Arc::new(Services {
+        store,
+        cache,
+        redis,
+        max_cacheable_bytes,
+        chunking,
+        metrics_handle,
+    });
     let app = Router::new()
         .route("/keys/:key", get(get_key))
         .route("/keys/:key", post(post_key))
+        .route("/metrics", get(metrics_handler))
         .with_state(services);
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
     info!("Server listening on 0.0.0.0:3000");
     axum::serve(listener, app).await.unwrap();
 }
 
-async fn get_key(
-    State(services): State<Arc<Services>>,
-    Path(key): Path<String>,
-) -> Result<Bytes, StatusCode> {
+async fn get_key(State(services): State<Arc<Services>>, Path(key): Path<String>) -> Response {
+    // `LocalCache` doesn't carry a checksum alongside its `Bytes` (see
+    // `store::cache::Entry`), so a hit here can't re-verify and never sets
+    // `CHECKSUM_HEADER`. The value was checked once on the way in, before
+    // this call first populated the cache.
     if let Some(content) = services.cache.get_item(&key) {
-        return Ok(content);
+        metrics::record_cache_hit();
+        return content.into_response();
+    }
+    metrics::record_cache_miss();
+
+    if let Some(redis) = &services.redis {
+        if let Some(cached) = redis.get(&key).await {
+            let checksum = cached.checksum.clone();
+            let content = cached.into_bytes();
+            if let Some(expected) = &checksum {
+                let actual = Checksum::compute(expected.algorithm, &content);
+                if actual.digest != expected.digest {
+                    tracing::error!(
+                        "Checksum mismatch serving key {} from Redis: expected {}, got {}",
+                        key,
+                        expected.digest,
+                        actual.digest
+                    );
+                    return StatusCode::BAD_GATEWAY.into_response();
+                }
+            }
+            services.cache.add_item(&key, content.clone());
+            return with_checksum_header(content.into_response(), checksum.as_ref());
+        }
     }
-    let res = services.store.get(&key).await;
-    match res {
+
+    if services.chunking.is_some() {
+        return get_chunked_key(&services, &key).await;
+    }
+
+    let s3_start = Instant::now();
+    let s3_result = services.store.get(&key).await;
+    metrics::record_s3_latency("get", s3_start.elapsed());
+    metrics::record_s3_result("get", s3_result.as_ref().map(|_| ()));
+    let object = match s3_result {
         Err(StoreError::ItemNotFound(key)) => {
             tracing::error!("Item {} not found", key);
-            Err(StatusCode::NOT_FOUND)
+            return StatusCode::NOT_FOUND.into_response();
         }
         Err(err) => {
             tracing::error!("Failed to get key: {}", err);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
         }
+        Ok(object) => object,
+    };
+
+    let checksum = object.checksum.clone();
+    let cacheable = object
+        .content_length
+        .is_some_and(|len| len <= services.max_cacheable_bytes);
+
+    if !cacheable {
+        return with_checksum_header(
+            Body::from_stream(object.stream).into_response(),
+            checksum.as_ref(),
+        );
+    }
+
+    match object.into_bytes().await {
         Ok(content) => {
             services.cache.add_item(&key, content.clone());
-            Ok(content)
+            if let Some(redis) = &services.redis {
+                let cache_value =
+                    CacheValue::new(&key, content.clone(), REDIS_TTL_SECONDS, checksum.clone());
+                redis.set(&key, &cache_value).await;
+            }
+            with_checksum_header(content.into_response(), checksum.as_ref())
+        }
+        Err(err @ StoreError::ChecksumMismatch { .. }) => {
+            tracing::error!("Checksum mismatch reading key {}: {}", key, err);
+            StatusCode::BAD_GATEWAY.into_response()
+        }
+        Err(err) => {
+            tracing::error!("Failed to read key: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Content-defined-chunking read path: reassembles the object from its
+/// manifest instead of reading a whole-object body. Chunk checksums aren't
+/// tracked per-chunk today, so no `X-Content-Checksum` header is set here.
+/// Reassembly itself isn't bounded by `max_cacheable_bytes` (the whole point
+/// of chunking is serving objects too big to cache whole), but the result is
+/// only pushed into `LocalCache`/Redis when it's small enough, same as the
+/// whole-object path.
+async fn get_chunked_key(services: &Arc<Services>, key: &str) -> Response {
+    let s3_start = Instant::now();
+    let result = services.store.get_chunked(key, &services.cache).await;
+    metrics::record_s3_latency("get", s3_start.elapsed());
+    metrics::record_s3_result("get", result.as_ref().map(|_| ()));
+    match result {
+        Ok(content) => {
+            if (content.len() as u64) <= services.max_cacheable_bytes {
+                services.cache.add_item(key, content.clone());
+                if let Some(redis) = &services.redis {
+                    let cache_value =
+                        CacheValue::new(key, content.clone(), REDIS_TTL_SECONDS, None);
+                    redis.set(key, &cache_value).await;
+                }
+            }
+            content.into_response()
+        }
+        Err(StoreError::ItemNotFound(key)) => {
+            tracing::error!("Item {} not found", key);
+            StatusCode::NOT_FOUND.into_response()
+        }
+        Err(err) => {
+            tracing::error!("Failed to get key: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+fn with_checksum_header(mut response: Response, checksum: Option<&Checksum>) -> Response {
+    if let Some(checksum) = checksum {
+        if let Ok(value) = HeaderValue::from_str(&checksum.header_value()) {
+            response.headers_mut().insert(CHECKSUM_HEADER, value);
         }
     }
+    response
+}
+
+/// Redis is an optional tier: if `REDIS_URL` isn't set, or the client
+/// fails to initialize, the server runs with just the local cache and S3.
+async fn init_redis() -> Option<RedisStore> {
+    let redis_url = std::env::var("REDIS_URL").ok()?;
+    match RedisStore::new(&redis_url, REDIS_TTL_SECONDS).await {
+        Ok(store) => Some(store),
+        Err(err) => {
+            tracing::warn!(
+                "Failed to initialize Redis store, continuing without it: {}",
+                err
+            );
+            None
+        }
+    }
+}
+
+fn max_cacheable_bytes() -> u64 {
+    std::env::var("MAX_CACHEABLE_BYTES")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_MAX_CACHEABLE_BYTES)
+}
+
+/// Picks the cache's eviction policy from `CACHE_EVICTION_POLICY` (`lru` or `lfu`),
+/// defaulting to LRU for any unset or unrecognized value.
+fn eviction_policy() -> EvictionPolicy {
+    match std::env::var("CACHE_EVICTION_POLICY") {
+        Ok(value) if value.eq_ignore_ascii_case("lfu") => EvictionPolicy::Lfu,
+        _ => EvictionPolicy::Lru,
+    }
+}
+
+/// Integrity checking is opt-in: `CHECKSUM_ALGORITHM` unset or `none` disables
+/// it, `crc32c` or `sha256` selects the algorithm tagged on every upload and
+/// verified on every buffered download.
+fn checksum_algorithm() -> Option<ChecksumAlgorithm> {
+    match std::env::var("CHECKSUM_ALGORITHM") {
+        Ok(value) if value.eq_ignore_ascii_case("crc32c") => Some(ChecksumAlgorithm::Crc32c),
+        Ok(value) if value.eq_ignore_ascii_case("sha256") => Some(ChecksumAlgorithm::Sha256),
+        _ => None,
+    }
+}
+
+/// Content-defined chunking is opt-in: `CHUNKED_STORAGE` unset or not `true`
+/// leaves every key stored as a single whole object (the default). Set it to
+/// switch both `post_key` and `get_key` over to chunked manifests with
+/// default chunk-size bounds.
+fn chunking_config() -> Option<ChunkingConfig> {
+    match std::env::var("CHUNKED_STORAGE") {
+        Ok(value) if value.eq_ignore_ascii_case("true") => Some(ChunkingConfig::default()),
+        _ => None,
+    }
 }
 
 async fn post_key(
     State(services): State<Arc<Services>>,
     Path(key): Path<String>,
-    payload: Bytes,
+    body: Body,
 ) -> StatusCode {
-    let res = services.store.set(&key, payload).await;
-    if res.is_err() {
-        tracing::error!("Failed to set key: {}", res.err().unwrap());
+    let s3_start = Instant::now();
+    let res = match &services.chunking {
+        Some(config) => match axum::body::to_bytes(body, MAX_CHUNKED_UPLOAD_BYTES).await {
+            Ok(bytes) => {
+                services
+                    .store
+                    .set_chunked(&key, bytes, config, &services.cache)
+                    .await
+            }
+            Err(err) => Err(StoreError::S3WriteError(err.to_string())),
+        },
+        None => services.store.set_stream(&key, body).await,
+    };
+    metrics::record_s3_latency("set", s3_start.elapsed());
+    metrics::record_s3_result("set", res.as_ref().map(|_| ()));
+    if let Err(err) = res {
+        tracing::error!("Failed to set key: {}", err);
         return StatusCode::INTERNAL_SERVER_ERROR;
     }
     StatusCode::CREATED
 }
+
+async fn metrics_handler(State(services): State<Arc<Services>>) -> String {
+    let stats = services.cache.stats();
+    metrics::record_cache_size(stats.entries as u64, stats.approx_bytes as u64);
+    services.metrics_handle.render()
+}
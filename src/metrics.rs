@@ -0,0 +1,48 @@
+use crate::store::s3_store::StoreError;
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use std::time::Duration;
+
+/// Installs the process-wide Prometheus recorder. The returned handle's
+/// `render()` produces the text exposition format served by `/metrics`.
+pub fn install() -> PrometheusHandle {
+    PrometheusBuilder::new()
+        .install_recorder()
+        .expect("failed to install Prometheus metrics recorder")
+}
+
+pub fn record_cache_hit() {
+    metrics::counter!("lake_cache_hits_total").increment(1);
+}
+
+pub fn record_cache_miss() {
+    metrics::counter!("lake_cache_misses_total").increment(1);
+}
+
+/// Records an S3 operation's outcome, labeled by the `StoreError` variant on failure.
+pub fn record_s3_result(operation: &'static str, result: Result<(), &StoreError>) {
+    match result {
+        Ok(()) => {
+            metrics::counter!("lake_cache_s3_requests_total", "op" => operation, "status" => "success")
+                .increment(1);
+        }
+        Err(err) => {
+            metrics::counter!(
+                "lake_cache_s3_requests_total",
+                "op" => operation,
+                "status" => "error",
+                "error" => err.variant_name(),
+            )
+            .increment(1);
+        }
+    }
+}
+
+pub fn record_s3_latency(operation: &'static str, elapsed: Duration) {
+    metrics::histogram!("lake_cache_s3_latency_seconds", "op" => operation)
+        .record(elapsed.as_secs_f64());
+}
+
+pub fn record_cache_size(entries: u64, approx_bytes: u64) {
+    metrics::gauge!("lake_cache_entries").set(entries as f64);
+    metrics::gauge!("lake_cache_bytes").set(approx_bytes as f64);
+}
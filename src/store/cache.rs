@@ -1,146 +1,279 @@
 use bytes::Bytes;
-use std::cell::RefCell;
 use std::collections::HashMap;
-use std::rc::{Rc, Weak};
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
 use std::time::{Duration, SystemTime};
-thread_local! {
-    static CACHE: RefCell<LRUCache> = RefCell::new(LRUCache::new(1, 0));
+
+/// Number of independent LRU shards backing a `LocalCache`. Splitting the
+/// keyspace across shards keeps lock contention low when many `get_key`
+/// handlers hit the cache concurrently, at the cost of a slightly coarser
+/// global eviction ordering (eviction order is only exact within a shard).
+const NUM_SHARDS: usize = 16;
+
+/// Which entry a shard evicts once it's over capacity.
+///
+/// `Services` picks one at startup and passes it to `LocalCache::new`; the
+/// `get_key`/`add_item` call sites don't need to know which policy is active.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// Evict the least recently used entry (default).
+    Lru,
+    /// Evict the least frequently used entry, breaking ties by age (oldest first).
+    /// Better suited to a small set of very hot files under scan-heavy access,
+    /// where plain LRU would otherwise evict them during a scan.
+    Lfu,
+}
+
+/// Process-wide, thread-safe cache shared by every Tokio worker.
+///
+/// Earlier versions kept the cache in a `thread_local!`, so each worker
+/// thread effectively had its own private copy. `LocalCache` instead owns
+/// a fixed set of shards, each guarded by its own `Mutex<LRUCache>`, so a
+/// single instance living in `Services` is visible to every request no
+/// matter which worker thread picks it up.
+pub struct LocalCache {
+    shards: Vec<Mutex<LRUCache>>,
 }
-pub struct LocalCache;
 
 impl LocalCache {
-    pub fn new(capacity: usize, ttl: u64) -> Self {
-        CACHE.with(|cache| {
-            let mut cache = cache.borrow_mut();
-            *cache = LRUCache::new(capacity, ttl);
-        });
-        LocalCache
+    pub fn new(capacity: usize, ttl: u64, policy: EvictionPolicy) -> Self {
+        let shard_capacity = (capacity / NUM_SHARDS).max(1);
+        let shards = (0..NUM_SHARDS)
+            .map(|_| Mutex::new(LRUCache::new(shard_capacity, ttl, policy)))
+            .collect();
+        LocalCache { shards }
+    }
+
+    pub fn get_item(&self, key: &str) -> Option<Bytes> {
+        self.shard_for(key).lock().unwrap().get_item(key)
+    }
+
+    pub fn add_item(&self, key: &str, value: Bytes) {
+        self.shard_for(key)
+            .lock()
+            .unwrap()
+            .add_item(key.to_string(), value)
     }
-    pub fn get_item(&self, key: &String) -> Option<Bytes> {
-        CACHE.with(|cache| cache.borrow_mut().get_item(key))
+
+    fn shard_for(&self, key: &str) -> &Mutex<LRUCache> {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        key.hash(&mut hasher);
+        let shard = (hasher.finish() as usize) % self.shards.len();
+        &self.shards[shard]
     }
 
-    pub fn add_item(&self, key: String, value: Bytes) {
-        CACHE.with(|cache| cache.borrow_mut().add_item(key, value))
+    /// Current entry count and approximate byte size across all shards, for
+    /// the `/metrics` gauges.
+    pub fn stats(&self) -> CacheStats {
+        let mut entries = 0;
+        let mut approx_bytes = 0;
+        for shard in &self.shards {
+            let shard = shard.lock().unwrap();
+            entries += shard.index.len();
+            approx_bytes += shard
+                .index
+                .values()
+                .filter_map(|&idx| shard.entries[idx].as_ref())
+                .map(|entry| entry.value.len())
+                .sum::<usize>();
+        }
+        CacheStats {
+            entries,
+            approx_bytes,
+        }
     }
 }
 
-struct Node {
+pub struct CacheStats {
+    pub entries: usize,
+    pub approx_bytes: usize,
+}
+
+/// A single cache entry, stored by index in `LRUCache::entries` rather than
+/// behind an `Rc<RefCell<_>>` so the structure stays `Send + Sync` and can
+/// live behind a shared `Mutex`. Deliberately carries no checksum: callers
+/// that need end-to-end integrity (see `store::checksum`) verify once on the
+/// way in, before the value ever reaches this cache.
+struct Entry {
     key: String,
     value: Bytes,
     expires_at: u64,
-    prev: Option<Weak<RefCell<Node>>>,
-    next: Option<Rc<RefCell<Node>>>,
+    /// Access count, maintained regardless of policy; only consulted under `Lfu`.
+    frequency: u64,
+    /// Insertion sequence number, used as the LFU tie-breaker (oldest first).
+    created_seq: u64,
+    prev: Option<usize>,
+    next: Option<usize>,
 }
 
+/// A cache with TTL expiry and a pluggable eviction policy, implemented as
+/// an index-based doubly linked list over a `Vec` so it contains no
+/// thread-confined types. The list itself always reflects recency of use
+/// (needed for `Lru`); `Lfu` instead scans `frequency`/`created_seq` at
+/// eviction time, which is cheap since eviction only runs when the shard is
+/// over its (small, per-shard) capacity.
 struct LRUCache {
     capacity: usize,
     ttl_seconds: u64,
-    map: HashMap<String, Rc<RefCell<Node>>>,
-    head: Option<Rc<RefCell<Node>>>,
-    tail: Option<Rc<RefCell<Node>>>,
+    policy: EvictionPolicy,
+    entries: Vec<Option<Entry>>,
+    index: HashMap<String, usize>,
+    free_list: Vec<usize>,
+    head: Option<usize>,
+    tail: Option<usize>,
+    next_seq: u64,
 }
 
 impl LRUCache {
-    /// Creates a new LRUCache with the given capacity.
-    fn new(capacity: usize, ttl_seconds: u64) -> Self {
+    /// Creates a new LRUCache with the given capacity and eviction policy.
+    fn new(capacity: usize, ttl_seconds: u64, policy: EvictionPolicy) -> Self {
         LRUCache {
-            capacity,
+            capacity: capacity.max(1),
             ttl_seconds,
-            map: HashMap::new(),
+            policy,
+            entries: Vec::new(),
+            index: HashMap::new(),
+            free_list: Vec::new(),
             head: None,
             tail: None,
+            next_seq: 0,
         }
     }
 
     /// Adds an item to the cache. If the item already exists, it updates the value and moves it to the front.
-    /// If adding the new item exceeds the capacity, it removes the least recently used item.
+    /// If adding the new item exceeds the capacity, it evicts an entry per the configured policy.
     fn add_item(&mut self, key: String, value: Bytes) {
-        if let Some(node) = self.map.get(&key) {
-            // Update the value and move the node to the head.
-            node.borrow_mut().value = value.clone();
-            self.move_to_head(Rc::clone(node));
-        } else {
-            // Create a new node.
-            let new_node = self.create_node(key.clone(), value);
-            // Add the new node to the front and insert it into the map.
-            self.add_to_head(Rc::clone(&new_node));
-            self.map.insert(key.clone(), Rc::clone(&new_node));
-
-            // If capacity is exceeded, remove the least recently used item.
-            if self.map.len() > self.capacity {
-                if let Some(tail_node) = self.tail.take() {
-                    let tail_key = tail_node.borrow().key.clone();
-                    self.remove_node(Rc::clone(&tail_node));
-                    self.map.remove(&tail_key);
-                }
-            }
+        if let Some(&idx) = self.index.get(&key) {
+            let entry = self.entries[idx].as_mut().expect("indexed entry must exist");
+            entry.value = value;
+            entry.expires_at = self.now_seconds() + self.ttl_seconds;
+            entry.frequency += 1;
+            self.move_to_head(idx);
+            return;
         }
-    }
 
-    /// Retrieves an item from the cache by key. If the item exists, it moves it to the front.
-    fn get_item(&mut self, key: &String) -> Option<Bytes> {
-        match self.map.get(key) {
-            Some(node) if self.now_seconds() > node.borrow().expires_at => {
-                self.remove_node(Rc::clone(node));
-                self.map.remove(key);
-                None
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let entry = Entry {
+            key: key.clone(),
+            value,
+            expires_at: self.now_seconds() + self.ttl_seconds,
+            frequency: 1,
+            created_seq: seq,
+            prev: None,
+            next: None,
+        };
+        let idx = match self.free_list.pop() {
+            Some(free_idx) => {
+                self.entries[free_idx] = Some(entry);
+                free_idx
             }
-            Some(node) => {
-                let value = node.borrow().value.clone();
-                self.move_to_head(Rc::clone(node));
-                Some(value)
+            None => {
+                self.entries.push(Some(entry));
+                self.entries.len() - 1
             }
-            None => None,
+        };
+        self.index.insert(key, idx);
+        self.push_front(idx);
+
+        if self.index.len() > self.capacity {
+            self.evict_one();
+        }
+    }
+
+    /// Retrieves an item from the cache by key. If the item exists, it moves it to the front
+    /// and bumps its access frequency.
+    fn get_item(&mut self, key: &str) -> Option<Bytes> {
+        let idx = *self.index.get(key)?;
+        let expired = self.entries[idx]
+            .as_ref()
+            .expect("indexed entry must exist")
+            .expires_at
+            < self.now_seconds();
+        if expired {
+            self.remove(idx);
+            return None;
         }
+        self.move_to_head(idx);
+        let entry = self.entries[idx].as_mut().unwrap();
+        entry.frequency += 1;
+        Some(entry.value.clone())
     }
 
-    /// Moves the given node to the front of the list.
-    fn move_to_head(&mut self, node: Rc<RefCell<Node>>) {
-        self.remove_node(Rc::clone(&node));
-        self.add_to_head(node);
+    /// Moves the given entry to the front of the list.
+    fn move_to_head(&mut self, idx: usize) {
+        if self.head == Some(idx) {
+            return;
+        }
+        self.detach(idx);
+        self.push_front(idx);
     }
 
-    /// Removes the given node from the list.
-    fn remove_node(&mut self, node: Rc<RefCell<Node>>) {
-        let prev_weak = node.borrow_mut().prev.take();
-        let next_opt = node.borrow_mut().next.take();
+    /// Unlinks the given entry from the list without freeing its slot.
+    fn detach(&mut self, idx: usize) {
+        let (prev, next) = {
+            let entry = self.entries[idx].as_ref().expect("indexed entry must exist");
+            (entry.prev, entry.next)
+        };
+        match prev {
+            Some(p) => self.entries[p].as_mut().unwrap().next = next,
+            None => self.head = next,
+        }
+        match next {
+            Some(n) => self.entries[n].as_mut().unwrap().prev = prev,
+            None => self.tail = prev,
+        }
+    }
 
-        if let Some(ref prev_weak_ref) = prev_weak {
-            if let Some(prev_rc) = prev_weak_ref.upgrade() {
-                prev_rc.borrow_mut().next = next_opt.clone();
-            }
-        } else {
-            // Node is head
-            self.head = next_opt.clone();
-        }
-
-        if let Some(next_rc) = next_opt {
-            next_rc.borrow_mut().prev = prev_weak.clone();
-        } else {
-            // Node is tail
-            if let Some(ref prev_weak_ref) = prev_weak {
-                self.tail = prev_weak_ref.upgrade();
-            } else {
-                // List is empty
-                self.tail = None;
-            }
+    /// Links the given entry in as the new head of the list.
+    fn push_front(&mut self, idx: usize) {
+        let old_head = self.head;
+        {
+            let entry = self.entries[idx].as_mut().expect("indexed entry must exist");
+            entry.prev = None;
+            entry.next = old_head;
+        }
+        match old_head {
+            Some(h) => self.entries[h].as_mut().unwrap().prev = Some(idx),
+            None => self.tail = Some(idx),
+        }
+        self.head = Some(idx);
+    }
+
+    /// Evicts one entry per the configured policy, if any entries exist.
+    fn evict_one(&mut self) {
+        match self.policy {
+            EvictionPolicy::Lru => self.evict_tail(),
+            EvictionPolicy::Lfu => self.evict_least_frequent(),
         }
     }
 
-    /// Adds the given node to the front of the list.
-    fn add_to_head(&mut self, node: Rc<RefCell<Node>>) {
-        node.borrow_mut().prev = None;
-        node.borrow_mut().next = self.head.clone();
+    /// Evicts the least recently used entry, if any.
+    fn evict_tail(&mut self) {
+        if let Some(idx) = self.tail {
+            self.remove(idx);
+        }
+    }
 
-        if let Some(old_head) = &self.head {
-            old_head.borrow_mut().prev = Some(Rc::downgrade(&node));
-        } else {
-            // List was empty, so tail is also node
-            self.tail = Some(Rc::clone(&node));
+    /// Evicts the least frequently used entry, breaking ties by age (oldest first).
+    fn evict_least_frequent(&mut self) {
+        let entries = &self.entries;
+        let victim = self.index.values().copied().min_by_key(|&idx| {
+            let entry = entries[idx].as_ref().expect("indexed entry must exist");
+            (entry.frequency, entry.created_seq)
+        });
+        if let Some(idx) = victim {
+            self.remove(idx);
         }
+    }
 
-        self.head = Some(node);
+    /// Removes an entry from the list and the index, returning its slot to the free list.
+    fn remove(&mut self, idx: usize) {
+        self.detach(idx);
+        if let Some(entry) = self.entries[idx].take() {
+            self.index.remove(&entry.key);
+        }
+        self.free_list.push(idx);
     }
 
     fn now_seconds(&self) -> u64 {
@@ -149,106 +282,126 @@ impl LRUCache {
             .unwrap()
             .as_secs()
     }
-    fn create_node(&self, key: String, value: Bytes) -> Rc<RefCell<Node>> {
-        Rc::new(RefCell::new(Node {
-            key: key.clone(),
-            value: value.clone(),
-            expires_at: self.now_seconds() + self.ttl_seconds,
-            prev: None,
-            next: None,
-        }))
-    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::Arc;
+    use std::thread;
     use std::thread::sleep;
-    use std::time::Duration;
 
     #[test]
     fn test_capacity_based_eviction() {
-        let cache = LocalCache::new(3, 60);
+        let mut cache = LRUCache::new(3, 60, EvictionPolicy::Lru);
 
         cache.add_item("key1".to_string(), Bytes::from("value1"));
         cache.add_item("key2".to_string(), Bytes::from("value2"));
         cache.add_item("key3".to_string(), Bytes::from("value3"));
 
-        assert_eq!(
-            cache.get_item(&"key1".to_string()),
-            Some(Bytes::from("value1"))
-        );
-        assert_eq!(
-            cache.get_item(&"key2".to_string()),
-            Some(Bytes::from("value2"))
-        );
-        assert_eq!(
-            cache.get_item(&"key3".to_string()),
-            Some(Bytes::from("value3"))
-        );
+        assert_eq!(cache.get_item("key1"), Some(Bytes::from("value1")));
+        assert_eq!(cache.get_item("key2"), Some(Bytes::from("value2")));
+        assert_eq!(cache.get_item("key3"), Some(Bytes::from("value3")));
 
         // Adding a fourth item should evict the least recently used item (key1)
         cache.add_item("key4".to_string(), Bytes::from("value4"));
 
-        assert_eq!(cache.get_item(&"key1".to_string()), None);
-        assert_eq!(
-            cache.get_item(&"key2".to_string()),
-            Some(Bytes::from("value2"))
-        );
-        assert_eq!(
-            cache.get_item(&"key3".to_string()),
-            Some(Bytes::from("value3"))
-        );
-        assert_eq!(
-            cache.get_item(&"key4".to_string()),
-            Some(Bytes::from("value4"))
-        );
+        assert_eq!(cache.get_item("key1"), None);
+        assert_eq!(cache.get_item("key2"), Some(Bytes::from("value2")));
+        assert_eq!(cache.get_item("key3"), Some(Bytes::from("value3")));
+        assert_eq!(cache.get_item("key4"), Some(Bytes::from("value4")));
     }
 
     #[test]
     fn test_get_item_updates_order() {
-        let cache = LocalCache::new(3, 60);
+        let mut cache = LRUCache::new(3, 60, EvictionPolicy::Lru);
 
         cache.add_item("key1".to_string(), Bytes::from("value1"));
         cache.add_item("key2".to_string(), Bytes::from("value2"));
         cache.add_item("key3".to_string(), Bytes::from("value3"));
 
         // Access key1, making it the most recently used
-        cache.get_item(&"key1".to_string());
+        cache.get_item("key1");
 
         // Add a new item, which should evict the least recently used (now key2)
         cache.add_item("key4".to_string(), Bytes::from("value4"));
 
-        assert_eq!(
-            cache.get_item(&"key1".to_string()),
-            Some(Bytes::from("value1"))
-        );
-        assert_eq!(cache.get_item(&"key2".to_string()), None);
-        assert_eq!(
-            cache.get_item(&"key3".to_string()),
-            Some(Bytes::from("value3"))
-        );
-        assert_eq!(
-            cache.get_item(&"key4".to_string()),
-            Some(Bytes::from("value4"))
-        );
+        assert_eq!(cache.get_item("key1"), Some(Bytes::from("value1")));
+        assert_eq!(cache.get_item("key2"), None);
+        assert_eq!(cache.get_item("key3"), Some(Bytes::from("value3")));
+        assert_eq!(cache.get_item("key4"), Some(Bytes::from("value4")));
     }
 
     #[test]
     fn test_ttl_expiration() {
-        let cache = LocalCache::new(3, 2); // TTL of 2 seconds
+        let mut cache = LRUCache::new(3, 2, EvictionPolicy::Lru); // TTL of 2 seconds
 
         cache.add_item("key1".to_string(), Bytes::from("value1"));
 
-        assert_eq!(
-            cache.get_item(&"key1".to_string()),
-            Some(Bytes::from("value1"))
-        );
+        assert_eq!(cache.get_item("key1"), Some(Bytes::from("value1")));
 
         // Wait for 3 seconds (longer than TTL)
         sleep(Duration::from_secs(3));
 
         // The item should now be expired
-        assert_eq!(cache.get_item(&"key1".to_string()), None);
+        assert_eq!(cache.get_item("key1"), None);
+    }
+
+    #[test]
+    fn test_lfu_eviction_prefers_least_frequently_used() {
+        let mut cache = LRUCache::new(3, 60, EvictionPolicy::Lfu);
+
+        cache.add_item("key1".to_string(), Bytes::from("value1"));
+        cache.add_item("key2".to_string(), Bytes::from("value2"));
+        cache.add_item("key3".to_string(), Bytes::from("value3"));
+
+        // key1 becomes the most frequently accessed entry, even though it's
+        // also the oldest by insertion order (an LRU cache would evict it first).
+        for _ in 0..5 {
+            cache.get_item("key1");
+        }
+
+        // Adding a fourth item exceeds capacity. key2 and key3 tie at the
+        // lowest frequency, so the older of the two (key2) is evicted.
+        cache.add_item("key4".to_string(), Bytes::from("value4"));
+
+        assert_eq!(cache.get_item("key1"), Some(Bytes::from("value1")));
+        assert_eq!(cache.get_item("key2"), None);
+        assert_eq!(cache.get_item("key3"), Some(Bytes::from("value3")));
+        assert_eq!(cache.get_item("key4"), Some(Bytes::from("value4")));
+    }
+
+    #[test]
+    fn test_local_cache_roundtrip() {
+        let cache = LocalCache::new(1000, 60, EvictionPolicy::Lru);
+
+        cache.add_item("key1", Bytes::from("value1"));
+        cache.add_item("key2", Bytes::from("value2"));
+
+        assert_eq!(cache.get_item("key1"), Some(Bytes::from("value1")));
+        assert_eq!(cache.get_item("key2"), Some(Bytes::from("value2")));
+        assert_eq!(cache.get_item("missing"), None);
+    }
+
+    #[test]
+    fn test_local_cache_visible_across_threads() {
+        // The whole point of moving off `thread_local!` is that a value
+        // cached by one worker thread is visible to requests served by
+        // another. Simulate that directly.
+        let cache = Arc::new(LocalCache::new(1000, 60, EvictionPolicy::Lru));
+
+        let writer = Arc::clone(&cache);
+        thread::spawn(move || {
+            writer.add_item("shared-key", Bytes::from("shared-value"));
+        })
+        .join()
+        .unwrap();
+
+        let reader = Arc::clone(&cache);
+        let result = thread::spawn(move || reader.get_item("shared-key"))
+            .join()
+            .unwrap();
+
+        assert_eq!(result, Some(Bytes::from("shared-value")));
     }
 }
@@ -0,0 +1,148 @@
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+/// Size bounds for content-defined chunking. `avg_chunk` controls the width
+/// of the rolling-hash boundary mask; `min_chunk`/`max_chunk` bound how small
+/// or large an individual chunk can get regardless of where the hash lands.
+#[derive(Debug, Clone, Copy)]
+pub struct ChunkingConfig {
+    pub min_chunk: usize,
+    pub avg_chunk: usize,
+    pub max_chunk: usize,
+}
+
+impl Default for ChunkingConfig {
+    fn default() -> Self {
+        ChunkingConfig {
+            min_chunk: 256 * 1024,
+            avg_chunk: 1024 * 1024,
+            max_chunk: 4 * 1024 * 1024,
+        }
+    }
+}
+
+/// The ordered list of chunk hashes making up a logical object, stored at its
+/// logical key in place of the object body. `get_chunked` fetches this first,
+/// then reassembles the object from `chunks/<hash>`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkManifest {
+    pub total_length: u64,
+    pub chunk_hashes: Vec<String>,
+}
+
+/// Splits a byte slice into content-defined chunks with a Gear rolling hash:
+/// a boundary is declared once the hash's low bits match a mask derived from
+/// `avg_chunk`, as long as at least `min_chunk` bytes have accumulated, or
+/// unconditionally once `max_chunk` bytes have accumulated. Because the
+/// boundary depends only on local content, inserting or removing bytes in
+/// one region of a later version doesn't shift chunk boundaries elsewhere,
+/// so unchanged regions across versions hash to the same chunks.
+pub struct Chunker {
+    min_chunk: usize,
+    max_chunk: usize,
+    mask: u64,
+}
+
+impl Chunker {
+    pub fn new(config: &ChunkingConfig) -> Self {
+        Chunker {
+            min_chunk: config.min_chunk,
+            max_chunk: config.max_chunk,
+            mask: mask_for_avg_chunk(config.avg_chunk),
+        }
+    }
+
+    pub fn split<'a>(&self, data: &'a [u8]) -> Vec<&'a [u8]> {
+        let gear = gear_table();
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        let mut hash: u64 = 0;
+
+        for (i, &byte) in data.iter().enumerate() {
+            hash = (hash << 1).wrapping_add(gear[byte as usize]);
+            let len = i + 1 - start;
+            let at_boundary = len >= self.min_chunk && hash & self.mask == 0;
+            if at_boundary || len >= self.max_chunk {
+                chunks.push(&data[start..=i]);
+                start = i + 1;
+                hash = 0;
+            }
+        }
+        if start < data.len() {
+            chunks.push(&data[start..]);
+        }
+        chunks
+    }
+}
+
+fn mask_for_avg_chunk(avg_chunk: usize) -> u64 {
+    let bits = (avg_chunk.max(2) as f64).log2().round() as u32;
+    (1u64 << bits.min(63)) - 1
+}
+
+/// A table of pseudo-random 64-bit constants, one per byte value, used by
+/// the Gear hash. Derived deterministically (not read from a file) so chunk
+/// boundaries are stable across processes and versions of this binary.
+fn gear_table() -> &'static [u64; 256] {
+    static TABLE: OnceLock<[u64; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = [0u64; 256];
+        let mut state: u64 = 0x9E3779B97F4A7C15;
+        for slot in table.iter_mut() {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            *slot = state;
+        }
+        table
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_respects_min_and_max_chunk() {
+        let config = ChunkingConfig {
+            min_chunk: 16,
+            avg_chunk: 32,
+            max_chunk: 64,
+        };
+        let chunker = Chunker::new(&config);
+        let data = vec![7u8; 1000];
+
+        let chunks = chunker.split(&data);
+
+        let reassembled: Vec<u8> = chunks.iter().flat_map(|c| c.iter().copied()).collect();
+        assert_eq!(reassembled, data);
+        for chunk in &chunks[..chunks.len() - 1] {
+            assert!(chunk.len() >= config.min_chunk);
+            assert!(chunk.len() <= config.max_chunk);
+        }
+    }
+
+    #[test]
+    fn test_unchanged_prefix_yields_identical_leading_chunks() {
+        let config = ChunkingConfig::default();
+        let chunker = Chunker::new(&config);
+
+        // Pseudo-random (xorshift) content: a short repeating pattern would
+        // never produce a Gear boundary, leaving the whole input as one chunk.
+        let mut base = vec![0u8; 3 * 1024 * 1024];
+        let mut state: u64 = 0x2545F4914F6CDD1D;
+        for byte in base.iter_mut() {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            *byte = (state >> 56) as u8;
+        }
+        let mut modified = base.clone();
+        modified.extend_from_slice(b"appended tail that only affects the end");
+
+        let base_chunks = chunker.split(&base);
+        let modified_chunks = chunker.split(&modified);
+
+        assert_eq!(base_chunks[0], modified_chunks[0]);
+    }
+}
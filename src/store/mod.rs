@@ -0,0 +1,6 @@
+pub mod cache;
+pub mod cache2;
+pub mod checksum;
+pub mod chunking;
+pub mod redis_store;
+pub mod s3_store;
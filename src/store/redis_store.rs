@@ -0,0 +1,137 @@
+use crate::store::checksum::Checksum;
+use crate::store::s3_store::StoreError;
+use bytes::Bytes;
+use redis::{
+    aio::MultiplexedConnection, AsyncCommands, Client, FromRedisValue, RedisResult, RedisWrite,
+    ToRedisArgs, Value,
+};
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// What we actually store in Redis for a cached key: the payload plus
+/// enough metadata to serve it without going back to S3.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheValue {
+    pub s3_key: String,
+    pub content_length: u64,
+    pub expires_at: u64,
+    pub checksum: Option<Checksum>,
+    pub body: Vec<u8>,
+}
+
+impl CacheValue {
+    pub fn new(s3_key: &str, body: Bytes, ttl_seconds: u64, checksum: Option<Checksum>) -> Self {
+        CacheValue {
+            s3_key: s3_key.to_string(),
+            content_length: body.len() as u64,
+            expires_at: now_seconds() + ttl_seconds,
+            checksum,
+            body: body.to_vec(),
+        }
+    }
+
+    pub fn into_bytes(self) -> Bytes {
+        Bytes::from(self.body)
+    }
+}
+
+impl ToRedisArgs for CacheValue {
+    fn write_redis_args<W>(&self, out: &mut W)
+    where
+        W: ?Sized + RedisWrite,
+    {
+        let encoded = bincode::serialize(self).expect("CacheValue is always serializable");
+        out.write_arg(&encoded);
+    }
+}
+
+impl FromRedisValue for CacheValue {
+    fn from_redis_value(v: &Value) -> RedisResult<Self> {
+        let bytes: Vec<u8> = redis::from_redis_value(v)?;
+        bincode::deserialize(&bytes).map_err(|err| {
+            (
+                redis::ErrorKind::TypeError,
+                "failed to decode CacheValue",
+                err.to_string(),
+            )
+                .into()
+        })
+    }
+}
+
+/// Cross-instance cache tier sitting between `LocalCache` and `S3Store`.
+/// Every operation degrades to a log + `None`/no-op on connection failure
+/// so a Redis outage never takes the server down, it just falls through
+/// to S3 as if Redis wasn't configured.
+pub struct RedisStore {
+    /// Established once in `new` and cloned per call -- that's the whole
+    /// point of a multiplexed connection, it's designed to be shared
+    /// cheaply across concurrent callers instead of reconnecting each time.
+    connection: MultiplexedConnection,
+    ttl_seconds: u64,
+}
+
+impl RedisStore {
+    pub async fn new(redis_url: &str, ttl_seconds: u64) -> Result<Self, StoreError> {
+        let client =
+            Client::open(redis_url).map_err(|err| StoreError::RedisError(err.to_string()))?;
+        let connection = client
+            .get_multiplexed_async_connection()
+            .await
+            .map_err(|err| StoreError::RedisError(err.to_string()))?;
+        Ok(RedisStore {
+            connection,
+            ttl_seconds,
+        })
+    }
+
+    pub async fn get(&self, key: &str) -> Option<CacheValue> {
+        let mut conn = self.connection.clone();
+        let value = match conn.get::<_, Option<CacheValue>>(key).await {
+            Ok(value) => value?,
+            Err(err) => {
+                tracing::warn!("Redis GET failed for {}: {}", key, err);
+                return None;
+            }
+        };
+
+        // Redis's own `SET EX` already owns expiry; these are a defensive
+        // second check against clock skew between the writer and reader or
+        // a corrupted payload, not the primary expiration mechanism.
+        if value.content_length != value.body.len() as u64 {
+            tracing::warn!(
+                "Redis entry for {} has inconsistent content_length ({} vs {} bytes), treating as a miss",
+                key,
+                value.content_length,
+                value.body.len()
+            );
+            return None;
+        }
+        if value.expires_at < now_seconds() {
+            tracing::warn!(
+                "Redis entry for {} is past its recorded expiry, treating as a miss",
+                key
+            );
+            return None;
+        }
+
+        Some(value)
+    }
+
+    pub async fn set(&self, key: &str, value: &CacheValue) {
+        let mut conn = self.connection.clone();
+        if let Err(err) = conn
+            .set_ex::<_, _, ()>(key, value, self.ttl_seconds)
+            .await
+        {
+            tracing::warn!("Redis SET failed for {}: {}", key, err);
+        }
+    }
+}
+
+fn now_seconds() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
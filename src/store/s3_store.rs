@@ -1,45 +1,114 @@
+use crate::store::cache::LocalCache;
+use crate::store::checksum::{Checksum, ChecksumAlgorithm};
+use crate::store::chunking::{ChunkManifest, ChunkingConfig, Chunker};
 use aws_config::BehaviorVersion;
 use aws_sdk_s3::{
     error::SdkError,
-    operation::{get_object::GetObjectError, put_object::PutObjectError},
+    operation::{
+        get_object::GetObjectError, head_object::HeadObjectError, put_object::PutObjectError,
+    },
     primitives::ByteStreamError,
     Client,
 };
-use bytes::Bytes;
+use axum::body::Body;
+use bytes::{Bytes, BytesMut};
+use futures::{Stream, TryStreamExt};
+use sha2::{Digest, Sha256};
+use std::pin::Pin;
 use thiserror::Error;
 
 pub struct S3Store {
     client: Client,
     bucket: String,
     store: String,
+    /// When set, uploads are tagged with this checksum algorithm and downloads
+    /// are verified against the digest S3 hands back. `None` disables integrity
+    /// checking entirely.
+    checksum_algorithm: Option<ChecksumAlgorithm>,
+}
+
+/// An S3 object's body, streamed rather than buffered in full. `content_length`
+/// comes straight from the `GetObject` response so callers can decide whether
+/// the object is small enough to also park in `LocalCache` without reading it.
+pub struct ObjectStream {
+    pub content_length: Option<u64>,
+    /// The checksum S3 reports for this object, if checksumming is enabled
+    /// and S3 returned one. Only checked once the stream is buffered via
+    /// `into_bytes` — a streamed pass-through of a large object is not
+    /// re-verified, since doing so would mean buffering it anyway.
+    pub checksum: Option<Checksum>,
+    pub stream: Pin<Box<dyn Stream<Item = Result<Bytes, StoreError>> + Send>>,
+}
+
+impl ObjectStream {
+    /// Buffers the whole stream into a single `Bytes`, verifying it against
+    /// `checksum` if one was supplied.
+    pub async fn into_bytes(self) -> Result<Bytes, StoreError> {
+        let checksum = self.checksum;
+        let chunks: Vec<Bytes> = self.stream.try_collect().await?;
+        let body = if chunks.len() == 1 {
+            chunks.into_iter().next().unwrap()
+        } else {
+            let mut buf = BytesMut::new();
+            for chunk in chunks {
+                buf.extend_from_slice(&chunk);
+            }
+            buf.freeze()
+        };
+
+        if let Some(expected) = checksum {
+            let actual = Checksum::compute(expected.algorithm, &body);
+            if actual.digest != expected.digest {
+                return Err(StoreError::ChecksumMismatch {
+                    expected: expected.digest,
+                    actual: actual.digest,
+                });
+            }
+        }
+
+        Ok(body)
+    }
 }
 
 impl S3Store {
-    pub async fn new(bucket: &str, store: &str) -> Self {
+    pub async fn new(bucket: &str, store: &str, checksum_algorithm: Option<ChecksumAlgorithm>) -> Self {
         let config = aws_config::load_defaults(BehaviorVersion::latest()).await;
         let client = aws_sdk_s3::Client::new(&config);
         Self {
             client,
             bucket: bucket.to_string(),
             store: store.to_string(),
+            checksum_algorithm,
         }
     }
 
     pub async fn set(&self, key: &str, body: Bytes) -> Result<(), StoreError> {
         let payload = aws_sdk_s3::primitives::ByteStream::from(body);
         let key = format!("{}/{}", self.store, key);
-        self.client
-            .put_object()
-            .bucket(self.bucket.clone())
-            .key(key)
-            .body(payload)
-            .send()
-            .await
-            .map_err(StoreError::from)?;
+        let mut req = self.client.put_object().bucket(self.bucket.clone()).key(key).body(payload);
+        if let Some(algorithm) = self.checksum_algorithm {
+            req = req.checksum_algorithm(algorithm.into());
+        }
+        req.send().await.map_err(StoreError::from)?;
+        Ok(())
+    }
+
+    /// Streams a request body straight through to S3 without materializing
+    /// it in memory, for large uploads.
+    pub async fn set_stream(&self, key: &str, body: Body) -> Result<(), StoreError> {
+        let payload = aws_sdk_s3::primitives::ByteStream::from_body_1_x(body);
+        let key = format!("{}/{}", self.store, key);
+        let mut req = self.client.put_object().bucket(self.bucket.clone()).key(key).body(payload);
+        if let Some(algorithm) = self.checksum_algorithm {
+            req = req.checksum_algorithm(algorithm.into());
+        }
+        req.send().await.map_err(StoreError::from)?;
         Ok(())
     }
 
-    pub async fn get(&self, key: &str) -> Result<Bytes, StoreError> {
+    /// Returns the object as a stream rather than buffering it, so large
+    /// lake files don't have to be held in memory all at once.
+    pub async fn get(&self, key: &str) -> Result<ObjectStream, StoreError> {
         let key = format!("{}/{}", self.store, key);
 
         let res = self
@@ -47,6 +116,7 @@ impl S3Store {
             .get_object()
             .bucket(self.bucket.clone())
             .key(key.clone())
+            .checksum_mode(aws_sdk_s3::types::ChecksumMode::Enabled)
             .send()
             .await;
 
@@ -62,9 +132,154 @@ impl S3Store {
             }
         }
 
-        let body = res?.body.collect().await?;
-        Ok(body.into_bytes())
+        let output = res?;
+        let content_length = output.content_length.map(|len| len as u64);
+        let checksum = self.checksum_algorithm.and_then(|algorithm| {
+            let digest = match algorithm {
+                ChecksumAlgorithm::Crc32c => output.checksum_crc32_c(),
+                ChecksumAlgorithm::Sha256 => output.checksum_sha256(),
+            }?;
+            Some(Checksum {
+                algorithm,
+                digest: digest.to_string(),
+            })
+        });
+        let stream = output
+            .body
+            .map_err(|err| StoreError::S3ReadError(err.to_string()));
+        Ok(ObjectStream {
+            content_length,
+            checksum,
+            stream: Box::pin(stream),
+        })
+    }
+
+    /// Writes `body` as content-defined chunks under `chunks/<sha256>`,
+    /// skipping any chunk that's already present, then writes a small
+    /// manifest at `key` listing the chunks in order. Whole-object `set` is
+    /// the default; call this explicitly for large, slowly-changing objects
+    /// where chunks are likely to be shared across versions. Freshly
+    /// uploaded chunks are also parked in `cache` by hash, since a caller
+    /// reading the object back right after writing it would otherwise have
+    /// to re-fetch every chunk from S3.
+    pub async fn set_chunked(
+        &self,
+        key: &str,
+        body: Bytes,
+        config: &ChunkingConfig,
+        cache: &LocalCache,
+    ) -> Result<(), StoreError> {
+        set_chunked_via(self, key, body, config, cache).await
+    }
+
+    /// Reads the manifest at `key` and reassembles the object from its
+    /// chunks, serving any chunk already present in `cache` (by hash)
+    /// instead of re-fetching it from S3.
+    pub async fn get_chunked(&self, key: &str, cache: &LocalCache) -> Result<Bytes, StoreError> {
+        get_chunked_via(self, key, cache).await
+    }
+
+    /// A chunk address (content hash) is immutable, so a `HEAD` hit means the
+    /// chunk is already stored and doesn't need to be re-uploaded (dedup).
+    async fn chunk_exists(&self, key: &str) -> Result<bool, StoreError> {
+        let key = format!("{}/{}", self.store, key);
+        match self
+            .client
+            .head_object()
+            .bucket(self.bucket.clone())
+            .key(key)
+            .send()
+            .await
+        {
+            Ok(_) => Ok(true),
+            Err(SdkError::ServiceError(service_error))
+                if matches!(service_error.err(), HeadObjectError::NotFound(_)) =>
+            {
+                Ok(false)
+            }
+            Err(err) => Err(StoreError::S3ReadError(err.to_string())),
+        }
+    }
+}
+
+/// The narrow slice of `S3Store` that `set_chunked`/`get_chunked` actually
+/// need. Pulled out as a trait (rather than calling `self` directly) so the
+/// chunking/dedup logic below can be driven by an in-memory fake in tests,
+/// without standing up a real S3 client.
+trait ChunkBackend {
+    async fn put_chunk(&self, key: &str, body: Bytes) -> Result<(), StoreError>;
+    async fn chunk_exists(&self, key: &str) -> Result<bool, StoreError>;
+    async fn get_chunk(&self, key: &str) -> Result<Bytes, StoreError>;
+}
+
+impl ChunkBackend for S3Store {
+    async fn put_chunk(&self, key: &str, body: Bytes) -> Result<(), StoreError> {
+        self.set(key, body).await
+    }
+
+    async fn chunk_exists(&self, key: &str) -> Result<bool, StoreError> {
+        self.chunk_exists(key).await
+    }
+
+    async fn get_chunk(&self, key: &str) -> Result<Bytes, StoreError> {
+        self.get(key).await?.into_bytes().await
+    }
+}
+
+async fn set_chunked_via(
+    backend: &impl ChunkBackend,
+    key: &str,
+    body: Bytes,
+    config: &ChunkingConfig,
+    cache: &LocalCache,
+) -> Result<(), StoreError> {
+    let chunker = Chunker::new(config);
+    let chunks = chunker.split(&body);
+
+    let mut chunk_hashes = Vec::with_capacity(chunks.len());
+    for chunk in &chunks {
+        let hash = hex::encode(Sha256::digest(chunk));
+        let chunk_key = format!("chunks/{}", hash);
+        if !backend.chunk_exists(&chunk_key).await? {
+            let chunk_bytes = Bytes::copy_from_slice(chunk);
+            backend.put_chunk(&chunk_key, chunk_bytes.clone()).await?;
+            cache.add_item(&chunk_key, chunk_bytes);
+        }
+        chunk_hashes.push(hash);
+    }
+
+    let manifest = ChunkManifest {
+        total_length: body.len() as u64,
+        chunk_hashes,
+    };
+    let manifest_bytes =
+        serde_json::to_vec(&manifest).map_err(|err| StoreError::S3WriteError(err.to_string()))?;
+    backend.put_chunk(key, Bytes::from(manifest_bytes)).await
+}
+
+async fn get_chunked_via(
+    backend: &impl ChunkBackend,
+    key: &str,
+    cache: &LocalCache,
+) -> Result<Bytes, StoreError> {
+    let manifest_bytes = backend.get_chunk(key).await?;
+    let manifest: ChunkManifest = serde_json::from_slice(&manifest_bytes)
+        .map_err(|err| StoreError::S3ReadError(err.to_string()))?;
+
+    let mut body = BytesMut::with_capacity(manifest.total_length as usize);
+    for hash in &manifest.chunk_hashes {
+        let chunk_key = format!("chunks/{}", hash);
+        let chunk = match cache.get_item(&chunk_key) {
+            Some(chunk) => chunk,
+            None => {
+                let chunk = backend.get_chunk(&chunk_key).await?;
+                cache.add_item(&chunk_key, chunk.clone());
+                chunk
+            }
+        };
+        body.extend_from_slice(&chunk);
     }
+    Ok(body.freeze())
 }
 
 #[derive(Error, Debug)]
@@ -75,7 +290,26 @@ pub enum StoreError {
     S3ReadError(String),
     #[error("Item {0} not found")]
     ItemNotFound(String),
+    #[error("Redis operation failed: {0}")]
+    RedisError(String),
+    #[error("Checksum mismatch: expected {expected}, got {actual}")]
+    ChecksumMismatch { expected: String, actual: String },
 }
+
+impl StoreError {
+    /// Short, stable label for metrics; avoids putting raw error strings
+    /// (which can be high-cardinality) into Prometheus label values.
+    pub fn variant_name(&self) -> &'static str {
+        match self {
+            StoreError::S3WriteError(_) => "s3_write_error",
+            StoreError::S3ReadError(_) => "s3_read_error",
+            StoreError::ItemNotFound(_) => "item_not_found",
+            StoreError::RedisError(_) => "redis_error",
+            StoreError::ChecksumMismatch { .. } => "checksum_mismatch",
+        }
+    }
+}
+
 impl From<SdkError<PutObjectError>> for StoreError {
     fn from(err: SdkError<PutObjectError>) -> Self {
         StoreError::S3WriteError(err.to_string())
@@ -91,3 +325,139 @@ impl From<ByteStreamError> for StoreError {
         StoreError::S3ReadError(err.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::cache::EvictionPolicy;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    /// In-memory stand-in for S3, so `set_chunked_via`/`get_chunked_via` can be
+    /// exercised without a real client. `put_calls` lets tests assert that a
+    /// dedup-hit chunk was never re-uploaded.
+    struct FakeBackend {
+        objects: Mutex<HashMap<String, Bytes>>,
+        put_calls: Mutex<u32>,
+    }
+
+    impl FakeBackend {
+        fn new() -> Self {
+            FakeBackend {
+                objects: Mutex::new(HashMap::new()),
+                put_calls: Mutex::new(0),
+            }
+        }
+    }
+
+    impl ChunkBackend for FakeBackend {
+        async fn put_chunk(&self, key: &str, body: Bytes) -> Result<(), StoreError> {
+            *self.put_calls.lock().unwrap() += 1;
+            self.objects.lock().unwrap().insert(key.to_string(), body);
+            Ok(())
+        }
+
+        async fn chunk_exists(&self, key: &str) -> Result<bool, StoreError> {
+            Ok(self.objects.lock().unwrap().contains_key(key))
+        }
+
+        async fn get_chunk(&self, key: &str) -> Result<Bytes, StoreError> {
+            self.objects
+                .lock()
+                .unwrap()
+                .get(key)
+                .cloned()
+                .ok_or_else(|| StoreError::ItemNotFound(key.to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_into_bytes_passes_through_on_matching_checksum() {
+        let body = Bytes::from_static(b"hello world");
+        let checksum = Checksum::compute(ChecksumAlgorithm::Sha256, &body);
+        let stream = futures::stream::iter(vec![Ok(body.clone())]);
+        let object = ObjectStream {
+            content_length: Some(body.len() as u64),
+            checksum: Some(checksum),
+            stream: Box::pin(stream),
+        };
+
+        let content = object.into_bytes().await.unwrap();
+
+        assert_eq!(content, body);
+    }
+
+    #[tokio::test]
+    async fn test_into_bytes_rejects_corrupted_body() {
+        let body = Bytes::from_static(b"hello world");
+        let wrong_checksum = Checksum {
+            algorithm: ChecksumAlgorithm::Sha256,
+            digest: "not-the-real-digest".to_string(),
+        };
+        let stream = futures::stream::iter(vec![Ok(body)]);
+        let object = ObjectStream {
+            content_length: None,
+            checksum: Some(wrong_checksum),
+            stream: Box::pin(stream),
+        };
+
+        let err = object.into_bytes().await.unwrap_err();
+
+        assert!(matches!(err, StoreError::ChecksumMismatch { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_set_chunked_then_get_chunked_roundtrips_and_dedups_shared_chunks() {
+        let backend = FakeBackend::new();
+        let cache = LocalCache::new(1000, 60, EvictionPolicy::Lru);
+        let config = ChunkingConfig {
+            min_chunk: 16,
+            avg_chunk: 32,
+            max_chunk: 64,
+        };
+
+        let mut data = vec![7u8; 500];
+        data.extend_from_slice(b"unique tail for the first object");
+        let body = Bytes::from(data);
+
+        set_chunked_via(&backend, "object-a", body.clone(), &config, &cache)
+            .await
+            .unwrap();
+        let uploads_after_first_write = *backend.put_calls.lock().unwrap();
+
+        // Same content under a different key: every chunk is already present
+        // by hash, so only the new manifest should be written.
+        set_chunked_via(&backend, "object-b", body.clone(), &config, &cache)
+            .await
+            .unwrap();
+        let uploads_after_second_write = *backend.put_calls.lock().unwrap();
+        assert_eq!(uploads_after_second_write, uploads_after_first_write + 1);
+
+        let roundtripped = get_chunked_via(&backend, "object-a", &cache).await.unwrap();
+        assert_eq!(roundtripped, body);
+    }
+
+    #[tokio::test]
+    async fn test_get_chunked_serves_from_cache_without_refetching() {
+        let backend = FakeBackend::new();
+        let cache = LocalCache::new(1000, 60, EvictionPolicy::Lru);
+        let config = ChunkingConfig {
+            min_chunk: 16,
+            avg_chunk: 32,
+            max_chunk: 64,
+        };
+        let body = Bytes::from(vec![3u8; 200]);
+
+        set_chunked_via(&backend, "object-a", body.clone(), &config, &cache)
+            .await
+            .unwrap();
+
+        // Wipe the backend's chunks (keeping only the manifest) but leave the
+        // cache populated: a read that hits the cache for every chunk only
+        // needs the manifest from the backend.
+        backend.objects.lock().unwrap().retain(|k, _| k == "object-a");
+
+        let roundtripped = get_chunked_via(&backend, "object-a", &cache).await.unwrap();
+        assert_eq!(roundtripped, body);
+    }
+}